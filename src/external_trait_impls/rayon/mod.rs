@@ -0,0 +1 @@
+mod sync_push_vec;
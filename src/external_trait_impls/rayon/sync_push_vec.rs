@@ -0,0 +1,16 @@
+use crate::sync_push_vec::Read;
+use rayon::iter::IntoParallelIterator;
+use std::alloc::Allocator;
+
+/// Reads are lock-free under a `Pin`, so the contiguous slice backing a [`Read`]
+/// handle can be split across threads with no synchronization, exactly like a
+/// plain `&[T]`.
+impl<'a, T: Sync, A: Allocator> IntoParallelIterator for Read<'a, T, A> {
+    type Item = &'a T;
+    type Iter = rayon::slice::Iter<'a, T>;
+
+    #[inline]
+    fn into_par_iter(self) -> Self::Iter {
+        self.as_slice().into_par_iter()
+    }
+}
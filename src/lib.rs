@@ -19,11 +19,14 @@ extern crate alloc;
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "rayon")]
+mod external_trait_impls;
 mod raw;
 mod scopeguard;
 mod util;
 
 pub mod sync_insert_table;
+pub mod sync_push_vec;
 
 /// The error type for `try_reserve` methods.
 #[derive(Clone, PartialEq, Eq, Debug)]
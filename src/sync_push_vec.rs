@@ -1,6 +1,7 @@
 use crate::{
     qsbr::{pin, Pin},
     scopeguard::guard,
+    TryReserveError,
 };
 use core::ptr::NonNull;
 use crossbeam_utils::atomic::AtomicCell;
@@ -20,29 +21,34 @@ use std::{
     sync::{atomic::AtomicUsize, Arc},
 };
 
+#[cfg(feature = "nightly")]
+use crate::UnavailableMutError;
+#[cfg(feature = "rayon")]
+use rayon::iter::IntoParallelIterator;
+
 mod code;
 mod tests;
 
 /// A reference to the table which can read from it. It is acquired either by a pin,
 /// or by exclusive access to the table.
-pub struct Read<'a, T> {
-    table: &'a SyncPushVec<T>,
+pub struct Read<'a, T, A: Allocator = Global> {
+    table: &'a SyncPushVec<T, A>,
 }
 
 /// A reference to the table which can write to it. It is acquired either by a lock,
 /// or by exclusive access to the table.
-pub struct Write<'a, T> {
-    table: &'a SyncPushVec<T>,
+pub struct Write<'a, T, A: Allocator = Global> {
+    table: &'a SyncPushVec<T, A>,
 }
 
 /// A reference to the table which can write to it. It is acquired either by a lock.
-pub struct LockedWrite<'a, T> {
-    table: Write<'a, T>,
+pub struct LockedWrite<'a, T, A: Allocator = Global> {
+    table: Write<'a, T, A>,
     _guard: MutexGuard<'a, ()>,
 }
 
-impl<'a, T> Deref for LockedWrite<'a, T> {
-    type Target = Write<'a, T>;
+impl<'a, T, A: Allocator> Deref for LockedWrite<'a, T, A> {
+    type Target = Write<'a, T, A>;
 
     #[inline]
     fn deref(&self) -> &Self::Target {
@@ -50,12 +56,14 @@ impl<'a, T> Deref for LockedWrite<'a, T> {
     }
 }
 
-pub struct SyncPushVec<T> {
+pub struct SyncPushVec<T, A: Allocator = Global> {
     current: AtomicCell<TableRef<T>>,
 
     lock: Mutex<()>,
 
-    old: UnsafeCell<Vec<Arc<DestroyTable<T>>>>,
+    old: UnsafeCell<Vec<Arc<DestroyTable<T, A>>>>,
+
+    alloc: A,
 
     // Tell dropck that we own instances of T.
     marker: PhantomData<T>,
@@ -116,13 +124,22 @@ impl<T> TableRef<T> {
     }
 
     #[inline]
-    fn allocate(capacity: usize) -> Self {
-        let (layout, _) = Self::layout(capacity).expect("capacity overflow");
+    fn allocate<A: Allocator>(alloc: &A, capacity: usize) -> Self {
+        match Self::try_allocate(alloc, capacity) {
+            Ok(table) => table,
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+        }
+    }
+
+    #[inline]
+    fn try_allocate<A: Allocator>(alloc: &A, capacity: usize) -> Result<Self, TryReserveError> {
+        let (layout, _) = Self::layout(capacity).map_err(|_| TryReserveError::CapacityOverflow)?;
 
-        let ptr: NonNull<u8> = Global
+        let ptr: NonNull<u8> = alloc
             .allocate(layout)
             .map(|ptr| ptr.cast())
-            .unwrap_or_else(|_| handle_alloc_error(layout));
+            .map_err(|_| TryReserveError::AllocError { layout })?;
 
         let mut result = Self {
             data: ptr.cast(),
@@ -136,11 +153,11 @@ impl<T> TableRef<T> {
             };
         }
 
-        result
+        Ok(result)
     }
 
     #[inline]
-    unsafe fn free(self) {
+    unsafe fn free<A: Allocator>(self, alloc: &A) {
         let items = self.info().items.load(Ordering::Relaxed);
         if items > 0 {
             if mem::needs_drop::<T>() {
@@ -148,7 +165,7 @@ impl<T> TableRef<T> {
                     self.data(i).drop_in_place();
                 }
             }
-            Global.deallocate(
+            alloc.deallocate(
                 self.data.cast(),
                 Self::layout(self.info().capacity).unwrap_unchecked().0,
             )
@@ -178,26 +195,27 @@ impl<T> TableRef<T> {
     }
 }
 
-struct DestroyTable<T> {
+struct DestroyTable<T, A: Allocator> {
     table: TableRef<T>,
+    alloc: A,
     lock: Mutex<bool>,
 }
 
-impl<T> DestroyTable<T> {
+impl<T, A: Allocator> DestroyTable<T, A> {
     unsafe fn run(&self) {
         let mut status = self.lock.lock();
         if !*status {
             *status = true;
-            self.table.free();
+            self.table.free(&self.alloc);
         }
     }
 }
 
-unsafe impl<#[may_dangle] T> Drop for SyncPushVec<T> {
+unsafe impl<#[may_dangle] T, A: Allocator> Drop for SyncPushVec<T, A> {
     #[inline]
     fn drop(&mut self) {
         unsafe {
-            self.current.load().free();
+            self.current.load().free(&self.alloc);
             for table in self.old.get_mut() {
                 table.run();
             }
@@ -205,13 +223,13 @@ unsafe impl<#[may_dangle] T> Drop for SyncPushVec<T> {
     }
 }
 
-unsafe impl<T: Send> Send for SyncPushVec<T> {}
-unsafe impl<T: Send> Sync for SyncPushVec<T> {}
+unsafe impl<T: Send, A: Allocator + Send> Send for SyncPushVec<T, A> {}
+unsafe impl<T: Send, A: Allocator + Sync> Sync for SyncPushVec<T, A> {}
 
-impl<T> Default for SyncPushVec<T> {
+impl<T, A: Allocator + Default> Default for SyncPushVec<T, A> {
     #[inline]
     fn default() -> Self {
-        Self::new()
+        Self::with_capacity_in(0, A::default())
     }
 }
 
@@ -223,15 +241,30 @@ impl<T> SyncPushVec<T> {
 
     #[inline]
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<T, A: Allocator> SyncPushVec<T, A> {
+    /// Creates a new, empty `SyncPushVec` using the given allocator.
+    #[inline]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_in(0, alloc)
+    }
+
+    /// Creates a new, empty `SyncPushVec` with the given capacity, using the given allocator.
+    #[inline]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
         Self {
             current: AtomicCell::new(if capacity > 0 {
-                TableRef::allocate(capacity)
+                TableRef::allocate(&alloc, capacity)
             } else {
                 TableRef::empty()
             }),
             old: UnsafeCell::new(Vec::new()),
             marker: PhantomData,
             lock: Mutex::new(()),
+            alloc,
         }
     }
 
@@ -248,23 +281,55 @@ impl<T> SyncPushVec<T> {
         }
     }
 
+    /// Returns mutable references to `N` distinct elements at once, given their indices.
+    ///
+    /// Because `&mut self` guarantees exclusive access to the table, this can hand
+    /// out several non-overlapping mutable references simultaneously: each index is
+    /// checked to be in bounds and distinct from every earlier index in the batch,
+    /// so the pointers handed out never alias.
+    #[cfg(feature = "nightly")]
+    pub fn get_each_mut<const N: usize>(
+        &mut self,
+        indices: [usize; N],
+    ) -> [Result<&mut T, UnavailableMutError>; N] {
+        let table = self.current.load();
+        let items = unsafe { table.info().items.load(Ordering::Acquire) };
+
+        let mut result: [Result<&mut T, UnavailableMutError>; N] =
+            [(); N].map(|_| Err(UnavailableMutError::Absent));
+
+        for i in 0..N {
+            let index = indices[i];
+
+            result[i] = if index >= items {
+                Err(UnavailableMutError::Absent)
+            } else if let Some(j) = indices[..i].iter().position(|&prior| prior == index) {
+                Err(UnavailableMutError::Duplicate(j))
+            } else {
+                Ok(unsafe { &mut *table.data(index) })
+            };
+        }
+
+        result
+    }
+
     #[inline]
     pub fn mutex(&self) -> &Mutex<()> {
         &self.lock
     }
 
     #[inline]
-    pub fn read<'a>(&'a self, _pin: &'a Pin) -> Read<'a, T> {
+    pub fn read<'a>(&'a self, _pin: &'a Pin) -> Read<'a, T, A> {
         Read { table: self }
     }
 
     #[inline]
-    pub unsafe fn unsafe_write(&self) -> Write<'_, T> {
+    pub unsafe fn unsafe_write(&self) -> Write<'_, T, A> {
         Write { table: self }
     }
 
     #[inline]
-    pub fn write(&mut self) -> Write<'_, T> {
+    pub fn write(&mut self) -> Write<'_, T, A> {
         Write { table: self }
     }
 
@@ -275,7 +340,7 @@ impl<T> SyncPushVec<T> {
     }
 
     #[inline]
-    pub fn lock(&self) -> LockedWrite<'_, T> {
+    pub fn lock(&self) -> LockedWrite<'_, T, A> {
         LockedWrite {
             table: Write { table: self },
             _guard: self.lock.lock(),
@@ -283,7 +348,7 @@ impl<T> SyncPushVec<T> {
     }
 
     #[inline]
-    pub fn lock_from_guard<'a>(&'a self, guard: MutexGuard<'a, ()>) -> LockedWrite<'a, T> {
+    pub fn lock_from_guard<'a>(&'a self, guard: MutexGuard<'a, ()>) -> LockedWrite<'a, T, A> {
         // Verify that we are target of the guard
         assert_eq!(
             &self.lock as *const _,
@@ -297,7 +362,7 @@ impl<T> SyncPushVec<T> {
     }
 }
 
-impl<'a, T> Read<'a, T> {
+impl<'a, T, A: Allocator> Read<'a, T, A> {
     /// Gets a reference to an element in the table.
     #[inline]
     pub fn get(&self, index: usize) -> Option<&'a T> {
@@ -330,27 +395,44 @@ impl<'a, T> Read<'a, T> {
         }
     }
 
+    /// Returns the elements of the table as a slice.
     #[inline]
-    pub fn iter(&self) -> Iter<'a, T> {
+    pub(crate) fn as_slice(&self) -> &'a [T] {
         let table = self.table.current.load();
         unsafe {
-            (*slice_from_raw_parts(
+            &*slice_from_raw_parts(
                 table.first() as *const T,
                 table.info().items.load(Ordering::Acquire),
-            ))
-            .iter()
+            )
         }
     }
+
+    #[inline]
+    pub fn iter(&self) -> Iter<'a, T> {
+        self.as_slice().iter()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Sync, A: Allocator> Read<'a, T, A> {
+    /// Returns a parallel iterator over the elements of the table.
+    ///
+    /// Reads are lock-free under a `Pin`, so rayon workers can split the index
+    /// range and read directly from the table with no extra synchronization.
+    #[inline]
+    pub fn par_iter(&self) -> rayon::slice::Iter<'a, T> {
+        self.as_slice().into_par_iter()
+    }
 }
 
-impl<'a, T> Write<'a, T> {
+impl<'a, T, A: Allocator> Write<'a, T, A> {
     #[inline]
-    pub fn read(&self) -> Read<'_, T> {
+    pub fn read(&self) -> Read<'_, T, A> {
         Read { table: self.table }
     }
 }
 
-impl<'a, T: Clone> Write<'a, T> {
+impl<'a, T: Clone, A: Allocator + Clone> Write<'a, T, A> {
     /// Inserts a new element into the end of the table, and returns a refernce to it.
     #[inline]
     pub fn push(&self, value: T) -> &'a T {
@@ -372,30 +454,100 @@ impl<'a, T: Clone> Write<'a, T> {
         }
     }
 
+    /// Inserts a new element into the end of the table, and returns a reference to it.
+    ///
+    /// Unlike [`push`](Write::push), this never aborts the process on allocation
+    /// failure. If reserving space fails, `value` is handed back to the caller
+    /// together with the error, and the table is left untouched.
+    #[inline]
+    pub fn try_push(&self, value: T) -> Result<&'a T, (T, TryReserveError)> {
+        let mut table = self.table.current.load();
+        unsafe {
+            let items = table.info().items.load(Ordering::Relaxed);
+
+            if unlikely(items == table.info().capacity) {
+                table = match self.try_reserve_one() {
+                    Ok(table) => table,
+                    Err(err) => return Err((value, err)),
+                };
+            }
+
+            let result = table.first().add(items);
+
+            result.write(value);
+
+            table.info().items.store(items + 1, Ordering::Release);
+
+            Ok(&*result)
+        }
+    }
+
     #[cold]
     #[inline(never)]
     fn reserve_one(&self) -> TableRef<T> {
         self.reserve(1)
     }
 
+    #[cold]
+    #[inline(never)]
+    fn try_reserve_one(&self) -> Result<TableRef<T>, TryReserveError> {
+        self.try_reserve_table(1)
+    }
+
     fn reserve(&self, additional: usize) -> TableRef<T> {
+        match self.try_reserve_table(additional) {
+            Ok(table) => table,
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+        }
+    }
+
+    /// Reserves capacity for at least `additional` more elements, returning an
+    /// error instead of aborting the process if allocation fails. On failure the
+    /// current table is left untouched.
+    #[inline]
+    pub fn try_reserve(&self, additional: usize) -> Result<(), TryReserveError> {
+        self.try_reserve_table(additional)?;
+        Ok(())
+    }
+
+    fn try_reserve_table(&self, additional: usize) -> Result<TableRef<T>, TryReserveError> {
         let table = self.table.current.load();
 
         let items = unsafe { table.info().items.load(Ordering::Relaxed) };
+        let capacity = unsafe { table.info().capacity };
+
+        let required = items
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+
+        if required <= capacity {
+            return Ok(table);
+        }
 
-        // Avoid `Option::ok_or_else` because it bloats LLVM IR.
-        let new_items = match items.checked_add(additional) {
-            Some(new_items) => new_items,
-            None => panic!("capacity overflow"),
+        // Grow geometrically, like `RawVec`, so that a sequence of `push` calls is
+        // amortized O(1) instead of reallocating and re-cloning every element on
+        // each insertion. Doubling can only overflow once `required` itself would
+        // already have to be huge, in which case falling back to `required` is safe
+        // since that value didn't overflow above.
+        let doubled = capacity.checked_mul(2).unwrap_or(required);
+        let min_non_zero_cap = if mem::size_of::<T>() == 1 {
+            8
+        } else if mem::size_of::<T>() <= 1024 {
+            4
+        } else {
+            1
         };
+        let new_cap = doubled.max(required).max(min_non_zero_cap);
 
-        let new_table = self.resize(new_items);
+        let new_table = self.try_resize(new_cap)?;
 
         self.table.current.store(new_table);
 
         pin(|pin| {
             let destroy = Arc::new(DestroyTable {
                 table,
+                alloc: self.table.alloc.clone(),
                 lock: Mutex::new(false),
             });
 
@@ -406,19 +558,19 @@ impl<'a, T: Clone> Write<'a, T> {
             }
         });
 
-        new_table
+        Ok(new_table)
     }
 
     /// Allocates a new table of a different size and moves the contents of the
-    /// current table into it.
-    fn resize(&self, capacity: usize) -> TableRef<T> {
+    /// current table into it, returning the allocation error instead of aborting.
+    fn try_resize(&self, capacity: usize) -> Result<TableRef<T>, TryReserveError> {
         let table = self.table.current.load();
 
         unsafe {
-            let mut new_table = TableRef::<T>::allocate(capacity);
+            let mut new_table = TableRef::<T>::try_allocate(&self.table.alloc, capacity)?;
 
             let mut guard = guard(Some(new_table), |new_table| {
-                new_table.map(|new_table| new_table.free());
+                new_table.map(|new_table| new_table.free(&self.table.alloc));
             });
 
             let iter = (*slice_from_raw_parts(
@@ -440,7 +592,106 @@ impl<'a, T: Clone> Write<'a, T> {
 
             *guard = None;
 
-            new_table
+            Ok(new_table)
         }
     }
+
+    /// Extends the table with the contents of an iterator, reserving space for
+    /// the iterator's lower bound up front. An iterator whose lower bound is its
+    /// exact length triggers at most one resize/clone pass; elements past the
+    /// lower bound grow the table the same way `push` does.
+    fn extend_impl<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let iter = iter.into_iter();
+
+        let (lower, _) = iter.size_hint();
+        if lower > 0 {
+            self.reserve(lower);
+        }
+
+        let mut table = self.table.current.load();
+        let mut items = unsafe { table.info().items.load(Ordering::Relaxed) };
+
+        for value in iter {
+            if unlikely(items == unsafe { table.info().capacity }) {
+                table = self.reserve_one();
+            }
+
+            unsafe {
+                table.first().add(items).write(value);
+            }
+
+            items += 1;
+
+            // Publish the new length per iteration, in case the iterator's `next`
+            // panics, so that already-written elements are still dropped.
+            unsafe {
+                table.info().items.store(items, Ordering::Release);
+            }
+        }
+    }
+}
+
+impl<'a, T: Clone, A: Allocator + Clone> Extend<T> for Write<'a, T, A> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.extend_impl(iter);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_one(&mut self, item: T) {
+        self.push(item);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_reserve(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+}
+
+impl<'a, T: Clone, A: Allocator + Clone> Extend<T> for LockedWrite<'a, T, A> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.table.extend_impl(iter);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_one(&mut self, item: T) {
+        self.table.push(item);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_reserve(&mut self, additional: usize) {
+        self.table.reserve(additional);
+    }
+}
+
+impl<T: Clone, A: Allocator + Clone> Extend<T> for SyncPushVec<T, A> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.write().extend_impl(iter);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_one(&mut self, item: T) {
+        self.write().push(item);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[inline]
+    fn extend_reserve(&mut self, additional: usize) {
+        self.write().reserve(additional);
+    }
+}
+
+impl<T: Clone> FromIterator<T> for SyncPushVec<T, Global> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        vec.extend(iter);
+        vec
+    }
 }
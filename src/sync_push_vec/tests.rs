@@ -0,0 +1,311 @@
+use super::*;
+use std::alloc::AllocError;
+use std::cell::Cell;
+
+/// An `Allocator` that fails once more than `remaining` bytes have been
+/// requested across its lifetime, used to exercise the fallible `try_*` paths
+/// without needing to actually exhaust memory.
+#[derive(Clone)]
+struct LimitedAlloc {
+    remaining: Cell<isize>,
+}
+
+impl LimitedAlloc {
+    fn new(remaining: isize) -> Self {
+        Self {
+            remaining: Cell::new(remaining),
+        }
+    }
+}
+
+unsafe impl Allocator for LimitedAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if (layout.size() as isize) > self.remaining.get() {
+            return Err(AllocError);
+        }
+        self.remaining.set(self.remaining.get() - layout.size() as isize);
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        Global.deallocate(ptr, layout)
+    }
+}
+
+#[test]
+fn try_push_hands_back_value_and_leaves_table_untouched_on_alloc_failure() {
+    let mut vec = SyncPushVec::with_capacity_in(0, LimitedAlloc::new(0));
+    let writer = vec.write();
+
+    match writer.try_push(42) {
+        Err((value, TryReserveError::AllocError { .. })) => assert_eq!(value, 42),
+        other => panic!("expected an AllocError carrying the value back, got {other:?}"),
+    }
+
+    assert_eq!(writer.read().len(), 0);
+    assert_eq!(writer.read().capacity(), 0);
+}
+
+#[test]
+fn try_reserve_leaves_table_untouched_on_alloc_failure() {
+    let mut vec = SyncPushVec::<i32, _>::with_capacity_in(0, LimitedAlloc::new(0));
+    let writer = vec.write();
+
+    let err = writer.try_reserve(4).unwrap_err();
+    assert!(matches!(err, TryReserveError::AllocError { .. }));
+
+    assert_eq!(writer.read().len(), 0);
+    assert_eq!(writer.read().capacity(), 0);
+}
+
+#[test]
+fn try_reserve_reports_capacity_overflow_instead_of_panicking() {
+    let mut vec = SyncPushVec::<u8>::new();
+    let writer = vec.write();
+
+    writer.push(1);
+
+    assert_eq!(
+        writer.try_reserve(usize::MAX).unwrap_err(),
+        TryReserveError::CapacityOverflow
+    );
+    assert_eq!(writer.read().len(), 1);
+}
+
+/// An `Allocator` that counts allocations and deallocations made through it,
+/// used to confirm a custom allocator is actually threaded through growth and
+/// deferred reclamation instead of falling back to `Global`.
+#[derive(Clone)]
+struct CountingAlloc {
+    allocs: Arc<AtomicUsize>,
+    deallocs: Arc<AtomicUsize>,
+}
+
+impl CountingAlloc {
+    fn new() -> Self {
+        Self {
+            allocs: Arc::new(AtomicUsize::new(0)),
+            deallocs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+unsafe impl Allocator for CountingAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = Global.allocate(layout)?;
+        self.allocs.fetch_add(1, Ordering::SeqCst);
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.deallocs.fetch_add(1, Ordering::SeqCst);
+        Global.deallocate(ptr, layout)
+    }
+}
+
+#[test]
+fn extend_reserves_once_up_front_for_a_sized_iterator() {
+    let mut vec = SyncPushVec::<i32>::new();
+    let mut writer = vec.write();
+
+    // A single upfront `reserve(10)` lands exactly on the geometric growth
+    // target for 10 elements. Growing one slot at a time (as repeated
+    // `push` would) passes through 4 and 8 first and ends up at 16 instead.
+    writer.extend(0..10);
+
+    assert_eq!(writer.read().len(), 10);
+    assert_eq!(writer.read().capacity(), 10);
+}
+
+#[test]
+fn from_iter_builds_a_vec_with_the_given_elements() {
+    let vec: SyncPushVec<i32> = (0..5).collect();
+
+    assert_eq!(vec.len(), 5);
+    pin(|p| {
+        assert_eq!(
+            vec.read(p).iter().copied().collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4]
+        );
+    });
+}
+
+#[test]
+fn extend_panicking_mid_iteration_drops_only_the_written_prefix() {
+    #[derive(Clone)]
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct PanicsAfterThree {
+        counter: Arc<AtomicUsize>,
+        yielded: usize,
+    }
+
+    impl Iterator for PanicsAfterThree {
+        type Item = DropCounter;
+
+        fn next(&mut self) -> Option<DropCounter> {
+            if self.yielded == 3 {
+                panic!("iterator exhausted its budget");
+            }
+            self.yielded += 1;
+            Some(DropCounter(self.counter.clone()))
+        }
+    }
+
+    let drop_count = Arc::new(AtomicUsize::new(0));
+
+    {
+        let mut vec = SyncPushVec::<DropCounter>::new();
+        let mut writer = vec.write();
+
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            writer.extend(PanicsAfterThree {
+                counter: drop_count.clone(),
+                yielded: 0,
+            });
+        }))
+        .is_err();
+
+        assert!(panicked, "the iterator's next() was expected to panic");
+        // The `// Publish the new length per iteration` path must have kept
+        // `items` in sync with what was actually written, even though the
+        // iterator never finished.
+        assert_eq!(writer.read().len(), 3);
+    }
+
+    // Dropping the vec must drop exactly the 3 already-written elements, once
+    // each: no double free of the written prefix, no leak of the rest.
+    assert_eq!(drop_count.load(Ordering::SeqCst), 3);
+}
+
+#[cfg(feature = "nightly")]
+#[test]
+fn get_each_mut_reports_absent_duplicate_and_hands_out_disjoint_refs() {
+    let mut vec = SyncPushVec::<i32>::new();
+    {
+        let writer = vec.write();
+        writer.push(1);
+        writer.push(2);
+        writer.push(3);
+    }
+
+    // Out of range.
+    let [a, b] = vec.get_each_mut([0, 5]);
+    assert!(matches!(a, Ok(&mut 1)));
+    assert_eq!(b, Err(UnavailableMutError::Absent));
+
+    // Repeated in-range index: `j` is the position of the earlier request for
+    // the same index, which is also where its reference sits in the result.
+    let [a, b] = vec.get_each_mut([0, 0]);
+    assert!(a.is_ok());
+    assert_eq!(b, Err(UnavailableMutError::Duplicate(0)));
+
+    let [a, b, c] = vec.get_each_mut([2, 0, 2]);
+    assert!(a.is_ok());
+    assert!(b.is_ok());
+    assert_eq!(c, Err(UnavailableMutError::Duplicate(0)));
+
+    // Genuinely disjoint mutable references: writing through one must not be
+    // observable through the other.
+    let [x, y] = vec.get_each_mut([0, 2]);
+    let x = x.unwrap();
+    let y = y.unwrap();
+    *x = 100;
+    assert_eq!(*y, 3);
+    *y = 200;
+    assert_eq!(*x, 100);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_iter_visits_every_element_like_the_sequential_iter() {
+    use rayon::prelude::*;
+
+    let mut vec = SyncPushVec::<i32>::new();
+    {
+        let writer = vec.write();
+        for i in 0..1000 {
+            writer.push(i);
+        }
+    }
+
+    pin(|p| {
+        let read = vec.read(p);
+
+        let sequential: i64 = read.iter().map(|&x| x as i64).sum();
+        let parallel: i64 = read.par_iter().map(|&x| x as i64).sum();
+        assert_eq!(parallel, sequential);
+
+        assert_eq!(read.par_iter().count(), read.len());
+    });
+}
+
+#[test]
+fn custom_allocator_is_used_for_growth_and_deferred_frees() {
+    let alloc = CountingAlloc::new();
+    let allocs = alloc.allocs.clone();
+    let deallocs = alloc.deallocs.clone();
+
+    {
+        let mut vec = SyncPushVec::with_capacity_in(0, alloc);
+        let writer = vec.write();
+
+        // Pushing past several capacity thresholds queues old tables for
+        // deferred QSBR reclamation; each of those tables was allocated
+        // through `alloc`, so it must also be freed through `alloc`.
+        for i in 0..20 {
+            writer.push(i);
+        }
+    }
+
+    let allocated = allocs.load(Ordering::SeqCst);
+    assert!(
+        allocated >= 2,
+        "expected at least one grow beyond the initial allocation, got {allocated}"
+    );
+    assert_eq!(
+        allocated,
+        deallocs.load(Ordering::SeqCst),
+        "every table allocated through the custom allocator (including ones queued \
+         for deferred reclamation) must be freed through it, not through `Global`"
+    );
+}
+
+#[test]
+fn push_grows_capacity_geometrically() {
+    let mut vec = SyncPushVec::<i32>::new();
+    let writer = vec.write();
+
+    assert_eq!(writer.read().capacity(), 0);
+
+    let mut capacities = Vec::new();
+    for i in 0..16 {
+        writer.push(i);
+        capacities.push(writer.read().capacity());
+    }
+
+    // Capacity must only change when a push actually needs more room, not on
+    // every single push, and when it does change it should double (with a
+    // small floor) rather than grow to the exact number of items needed.
+    let mut distinct = capacities.clone();
+    distinct.dedup();
+    assert!(
+        distinct.len() < capacities.len(),
+        "capacity changed on every push: {:?}",
+        capacities
+    );
+
+    for pair in distinct.windows(2) {
+        assert!(
+            pair[1] >= pair[0] * 2,
+            "capacity should double on growth, got {:?}",
+            pair
+        );
+    }
+}